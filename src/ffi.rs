@@ -0,0 +1,234 @@
+//! C FFI surface for driving the resize/crop pipeline from non-Rust callers (e.g. Python
+//! data-prep scripts), mirroring the batch-job pattern used by `parallel_image_crop`: a flat
+//! array of (path, target-resolution-list) jobs goes in, and a C-compatible array of
+//! (output path, error) results comes back, all run through the same rayon parallel loop the
+//! CLI uses. Enabled with the `ffi` feature.
+
+use crate::{
+    crop_image_to_resolutions, FitMode, Gravity, OutputFormat, ProcessedImage, SnapcropConfig,
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::ffi::{c_char, CStr, CString};
+use std::path::Path;
+use std::ptr;
+
+/// A single (source path, target resolutions) job, as passed in from C.
+#[repr(C)]
+pub struct SnapcropJob {
+    /// NUL-terminated path to the source image.
+    pub path: *const c_char,
+    /// Flattened `[w0, h0, w1, h1, ...]` target resolution pairs.
+    pub resolutions: *const u32,
+    pub resolutions_len: usize,
+}
+
+/// Shared processing options for a batch, as passed in from C. Mirrors [`SnapcropConfig`] with
+/// FFI-safe (numeric) field types.
+#[repr(C)]
+pub struct SnapcropConfigFfi {
+    /// 0 = cover, 1 = pad, 2 = stretch.
+    pub mode: u8,
+    pub pad_color: [u8; 3],
+    /// 0=center, 1=north, 2=south, 3=east, 4=west, 5=northeast, 6=northwest, 7=southeast, 8=southwest.
+    pub gravity: u8,
+    pub smart: u8,
+    pub force_rgb: u8,
+    pub trim: u8,
+    pub trim_tolerance: u8,
+    pub trim_color_set: u8,
+    pub trim_color: [u8; 3],
+    /// 0 = png, 1 = jpeg, 2 = webp.
+    pub image_format: u8,
+    pub quality: u8,
+}
+
+impl TryFrom<&SnapcropConfigFfi> for SnapcropConfig {
+    type Error = anyhow::Error;
+
+    fn try_from(ffi: &SnapcropConfigFfi) -> Result<Self, Self::Error> {
+        Ok(SnapcropConfig {
+            resolutions: Vec::new(),
+            mode: match ffi.mode {
+                0 => FitMode::Cover,
+                1 => FitMode::Pad,
+                2 => FitMode::Stretch,
+                other => anyhow::bail!("Invalid mode code: {other}"),
+            },
+            pad_color: ffi.pad_color,
+            gravity: match ffi.gravity {
+                0 => Gravity::Center,
+                1 => Gravity::North,
+                2 => Gravity::South,
+                3 => Gravity::East,
+                4 => Gravity::West,
+                5 => Gravity::NorthEast,
+                6 => Gravity::NorthWest,
+                7 => Gravity::SouthEast,
+                8 => Gravity::SouthWest,
+                other => anyhow::bail!("Invalid gravity code: {other}"),
+            },
+            smart: ffi.smart != 0,
+            force_rgb: ffi.force_rgb != 0,
+            trim: ffi.trim != 0,
+            trim_tolerance: ffi.trim_tolerance,
+            trim_color: (ffi.trim_color_set != 0).then_some(ffi.trim_color),
+            image_format: match ffi.image_format {
+                0 => OutputFormat::Png,
+                1 => OutputFormat::Jpeg,
+                2 => OutputFormat::Webp,
+                other => anyhow::bail!("Invalid format code: {other}"),
+            },
+            quality: ffi.quality,
+        })
+    }
+}
+
+/// Result of processing one job: `output_path` is null on failure, `error` is null on success.
+/// Both are heap-allocated, NUL-terminated C strings owned by the enclosing
+/// [`SnapcropResultArray`]; free them by passing the array to [`snapcrop_free_results`].
+#[repr(C)]
+pub struct SnapcropResult {
+    pub output_path: *mut c_char,
+    pub error: *mut c_char,
+}
+
+/// A heap-allocated array of [`SnapcropResult`], one per input job, in the same order, or a
+/// single error result when the whole batch is rejected.
+#[repr(C)]
+pub struct SnapcropResultArray {
+    pub results: *mut SnapcropResult,
+    pub len: usize,
+}
+
+fn to_c_string(s: &str) -> *mut c_char {
+    CString::new(s).unwrap_or_default().into_raw()
+}
+
+fn single_result_array(message: &str) -> SnapcropResultArray {
+    let results = vec![SnapcropResult {
+        output_path: ptr::null_mut(),
+        error: to_c_string(message),
+    }];
+    let ptr = Box::into_raw(results.into_boxed_slice()) as *mut SnapcropResult;
+    SnapcropResultArray { results: ptr, len: 1 }
+}
+
+/// # Safety
+/// `ptr` must be a valid, NUL-terminated UTF-8 C string, or null.
+unsafe fn cstr_to_path<'a>(ptr: *const c_char) -> anyhow::Result<&'a Path> {
+    if ptr.is_null() {
+        return Err(anyhow::anyhow!("Null path pointer"));
+    }
+    Ok(Path::new(CStr::from_ptr(ptr).to_str()?))
+}
+
+/// `Send`-safe stand-in for [`SnapcropResult`] used while the batch is still running in
+/// parallel; the raw `*mut c_char` fields are only materialized afterwards, on the collecting
+/// thread (see [`snapcrop_process_batch`]).
+struct JobOutcome {
+    output_path: Option<String>,
+    error: Option<String>,
+}
+
+impl JobOutcome {
+    fn into_result(self) -> SnapcropResult {
+        SnapcropResult {
+            output_path: self.output_path.map_or(ptr::null_mut(), |s| to_c_string(&s)),
+            error: self.error.map_or(ptr::null_mut(), |s| to_c_string(&s)),
+        }
+    }
+}
+
+/// # Safety
+/// `job.path` must be a valid, NUL-terminated UTF-8 C string, and `job.resolutions` must point to
+/// `job.resolutions_len` valid `u32`s.
+unsafe fn run_job(job: &SnapcropJob, output_path: &Path, config: &SnapcropConfig) -> JobOutcome {
+    let outcome = (|| -> anyhow::Result<ProcessedImage> {
+        let path = cstr_to_path(job.path)?;
+        if job.resolutions.is_null() || job.resolutions_len == 0 || job.resolutions_len % 2 != 0 {
+            anyhow::bail!("Invalid resolutions array");
+        }
+        let flat = std::slice::from_raw_parts(job.resolutions, job.resolutions_len);
+        let resolutions = flat.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect();
+
+        let mut job_config = config.clone();
+        job_config.resolutions = resolutions;
+
+        crop_image_to_resolutions(path, output_path, &job_config)
+    })();
+
+    match outcome {
+        Ok(processed) => JobOutcome {
+            output_path: Some(processed.output_path.to_string_lossy().into_owned()),
+            error: None,
+        },
+        Err(e) => JobOutcome {
+            output_path: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Runs `jobs` through the same rayon parallel pipeline the CLI uses, writing outputs under
+/// `output_path`. Returns one [`SnapcropResult`] per job, in the same order; if an argument is
+/// null, `output_path` is not valid UTF-8, or `config` holds an unknown code, it instead returns
+/// a single result whose `error` says why. Free the returned array with [`snapcrop_free_results`].
+///
+/// # Safety
+/// `jobs` must point to `jobs_len` valid [`SnapcropJob`]s, each with a NUL-terminated UTF-8
+/// `path` and a `resolutions` array of `resolutions_len` `u32`s (an even count of width/height
+/// pairs). `output_path` and `config` must be valid, non-null pointers.
+#[no_mangle]
+pub unsafe extern "C" fn snapcrop_process_batch(
+    jobs: *const SnapcropJob,
+    jobs_len: usize,
+    output_path: *const c_char,
+    config: *const SnapcropConfigFfi,
+) -> SnapcropResultArray {
+    if jobs.is_null() || config.is_null() || output_path.is_null() {
+        return single_result_array("Null argument to snapcrop_process_batch");
+    }
+
+    let output_path = match unsafe { cstr_to_path(output_path) } {
+        Ok(path) => path,
+        Err(e) => return single_result_array(&e.to_string()),
+    };
+
+    let config = match SnapcropConfig::try_from(unsafe { &*config }) {
+        Ok(config) => config,
+        Err(e) => return single_result_array(&e.to_string()),
+    };
+
+    let jobs = unsafe { std::slice::from_raw_parts(jobs, jobs_len) };
+
+    let outcomes: Vec<JobOutcome> = jobs
+        .par_iter()
+        .map(|job| unsafe { run_job(job, output_path, &config) })
+        .collect();
+
+    let results: Vec<SnapcropResult> = outcomes.into_iter().map(JobOutcome::into_result).collect();
+    let len = results.len();
+    let ptr = Box::into_raw(results.into_boxed_slice()) as *mut SnapcropResult;
+    SnapcropResultArray { results: ptr, len }
+}
+
+/// Frees a [`SnapcropResultArray`] and every `output_path`/`error` string it owns.
+///
+/// # Safety
+/// `array` must be a value previously returned by [`snapcrop_process_batch`], and must not be
+/// freed more than once.
+#[no_mangle]
+pub unsafe extern "C" fn snapcrop_free_results(array: SnapcropResultArray) {
+    let slice_ptr = ptr::slice_from_raw_parts_mut(array.results, array.len);
+    let results = unsafe { Box::from_raw(slice_ptr) };
+    for result in results.into_vec() {
+        unsafe {
+            if !result.output_path.is_null() {
+                drop(CString::from_raw(result.output_path));
+            }
+            if !result.error.is_null() {
+                drop(CString::from_raw(result.error));
+            }
+        }
+    }
+}
@@ -0,0 +1,823 @@
+//! Core resize/crop pipeline, usable as a library independently of the CLI `main.rs`.
+//!
+//! [`SnapcropConfig`] collects the knobs the CLI exposes as flags, and
+//! [`crop_image_to_resolutions`] runs one source image through the pipeline: decode, optional
+//! trim, fit/crop to the closest requested resolution, and encode. This lets data-prep scripts
+//! (including, via the `ffi` feature, non-Rust callers) drive the pipeline in-process instead of
+//! shelling out per image.
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+use anyhow::{anyhow, Context, Result};
+use blake3::hash;
+use clap::ValueEnum;
+use fast_image_resize as fr;
+use image::io::Reader as ImageReader;
+use image::{ImageBuffer, Rgb, Rgba, RgbImage, RgbaImage};
+use nom::branch::alt;
+use nom::character::complete::{char, digit1, multispace0};
+use nom::combinator::{map, map_res, opt};
+use nom::multi::separated_list0;
+use nom::sequence::{preceded, separated_pair, terminated, tuple};
+use nom::IResult;
+use log::debug;
+use std::cmp::Ordering;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::fs;
+
+fn parse_u32(input: &str) -> IResult<&str, u32> {
+    map_res(digit1, |digit_str: &str| digit_str.parse::<u32>())(input)
+}
+
+fn parse_bidirectional_resolution(input: &str) -> IResult<&str, Vec<(u32, u32)>> {
+    map(
+        preceded(char('['), terminated(parse_sizes, char(']'))),
+        |res| {
+            res.iter()
+                .flat_map(|&(width, height)| vec![(width, height), (height, width)])
+                .collect()
+        },
+    )(input)
+}
+
+fn parse_resolution(input: &str) -> IResult<&str, Vec<(u32, u32)>> {
+    alt((parse_sizes, parse_bidirectional_resolution))(input)
+}
+
+fn parse_range(input: &str) -> IResult<&str, Vec<u32>> {
+    let (input, (start, _, end, step)) = tuple((
+        parse_u32,
+        char(':'),
+        parse_u32,
+        opt(preceded(char(':'), parse_u32)),
+    ))(input)?;
+    Ok((input, generate_values((start, end, step.unwrap_or(1)))))
+}
+
+fn generate_values((start, end, step): (u32, u32, u32)) -> Vec<u32> {
+    (start..=end).step_by(step as usize).collect()
+}
+
+fn parse_size(input: &str) -> IResult<&str, Vec<u32>> {
+    alt((parse_range, map(parse_u32, |size| vec![size])))(input)
+}
+
+fn parse_sizes(input: &str) -> IResult<&str, Vec<(u32, u32)>> {
+    let (input, (widths, heights)) = alt((
+        separated_pair(parse_size, char('x'), parse_size),
+        map(parse_size, |sizes| (sizes.clone(), sizes.clone())),
+    ))(input)?;
+
+    Ok((
+        input,
+        widths
+            .iter()
+            .flat_map(|w| heights.iter().map(|h| (*w, *h)))
+            .collect(),
+    ))
+}
+
+fn parse_resolutions_nom(input: &str) -> IResult<&str, Vec<(u32, u32)>> {
+    let (input, res_list) =
+        separated_list0(terminated(char(','), multispace0), parse_resolution)(input)?;
+    Ok((input, res_list.into_iter().flatten().collect()))
+}
+
+/// Parses a `--res`-style resolution spec (ranges, lists, bidirectional brackets) into concrete
+/// `(width, height)` pairs.
+pub fn parse_resolutions(input: &str) -> Result<Vec<(u32, u32)>> {
+    parse_resolutions_nom(input)
+        .map_err(|e| anyhow!("Failed to parse resolutions: {}", e))
+        .map(|(_, res)| res)
+}
+
+/// Parses a "R,G,B" string into byte channels, used for `--pad-color`/`--trim-color`.
+pub fn parse_color(input: &str) -> Result<[u8; 3]> {
+    let parts: Vec<&str> = input.split(',').collect();
+    if parts.len() != 3 {
+        return Err(anyhow!(
+            "Invalid color '{}', expected 3 comma-separated channels",
+            input
+        ));
+    }
+
+    let mut channels = [0u8; 3];
+    for (channel, part) in channels.iter_mut().zip(parts) {
+        *channel = part
+            .trim()
+            .parse::<u8>()
+            .with_context(|| format!("Invalid color channel '{}'", part))?;
+    }
+    Ok(channels)
+}
+
+/// Output image format, explicitly driving encoder selection instead of relying on the path
+/// extension inferred by `image::save`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Where to anchor the `cover`-mode crop box within the resized, over-sized image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Gravity {
+    Center,
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl Gravity {
+    /// The `(x, y)` centering fractions expected by `fr::DynamicImageView::set_crop_box_to_fit_dst_size`.
+    fn centering(self) -> (f64, f64) {
+        match self {
+            Gravity::Center => (0.5, 0.5),
+            Gravity::North => (0.5, 0.0),
+            Gravity::South => (0.5, 1.0),
+            Gravity::West => (0.0, 0.5),
+            Gravity::East => (1.0, 0.5),
+            Gravity::NorthWest => (0.0, 0.0),
+            Gravity::NorthEast => (1.0, 0.0),
+            Gravity::SouthWest => (0.0, 1.0),
+            Gravity::SouthEast => (1.0, 1.0),
+        }
+    }
+}
+
+/// How a source image is fit into its chosen target resolution.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FitMode {
+    /// Scale to fill the target, then crop the overhang according to `gravity` (or the `smart`
+    /// window); center gravity is the previous default behavior. Skips sources smaller than the
+    /// target.
+    Cover,
+    /// Scale to fit entirely inside the target and pad the remainder with `pad_color`.
+    #[value(alias = "contain")]
+    Pad,
+    /// Resize directly to the target dimensions, ignoring the source aspect ratio.
+    Stretch,
+}
+
+/// Options controlling how [`crop_image_to_resolutions`] fits, crops and encodes a source image.
+#[derive(Clone, Debug)]
+pub struct SnapcropConfig {
+    /// Candidate target resolutions; the one with the closest aspect ratio to the source wins.
+    pub resolutions: Vec<(u32, u32)>,
+    /// How to fit the source into the chosen target resolution.
+    pub mode: FitMode,
+    /// Background color used to fill the padding in `Pad` mode.
+    pub pad_color: [u8; 3],
+    /// Crop gravity for `Cover` mode, ignored when `smart` is set.
+    pub gravity: Gravity,
+    /// Pick the `Cover` crop window that maximizes visual detail instead of a fixed gravity.
+    pub smart: bool,
+    /// Flatten to 8-bit RGB, discarding alpha and extra bit depth.
+    pub force_rgb: bool,
+    /// Strip uniform-color borders before resizing.
+    pub trim: bool,
+    /// Maximum per-channel color distance from the background still considered uniform.
+    pub trim_tolerance: u8,
+    /// Override the auto-detected background color used by `trim`.
+    pub trim_color: Option<[u8; 3]>,
+    /// Output image format, selects the encoder directly.
+    pub image_format: OutputFormat,
+    /// JPEG encoding quality, 1-100. No effect on PNG or WebP, which the `image` crate always
+    /// writes lossless.
+    pub quality: u8,
+}
+
+impl Default for SnapcropConfig {
+    fn default() -> Self {
+        Self {
+            resolutions: Vec::new(),
+            mode: FitMode::Cover,
+            pad_color: [0, 0, 0],
+            gravity: Gravity::Center,
+            smart: false,
+            force_rgb: false,
+            trim: false,
+            trim_tolerance: 10,
+            trim_color: None,
+            image_format: OutputFormat::Png,
+            quality: 90,
+        }
+    }
+}
+
+fn resize_image(
+    src_view: fr::DynamicImageView,
+    resize_w: NonZeroU32,
+    resize_h: NonZeroU32,
+) -> Result<fr::Image> {
+    let mut dst_image = fr::Image::new(resize_w, resize_h, src_view.pixel_type());
+    let mut dst_view = dst_image.view_mut();
+    let mut resizer = fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3));
+    resizer.resize(&src_view, &mut dst_view)?;
+    Ok(dst_image)
+}
+
+fn crop_image(
+    mut src_view: fr::DynamicImageView,
+    crop_w: NonZeroU32,
+    crop_h: NonZeroU32,
+    centering: (f64, f64),
+) -> Result<fr::Image> {
+    src_view.set_crop_box_to_fit_dst_size(crop_w, crop_h, Some(centering));
+    let mut dst_image = fr::Image::new(crop_w, crop_h, src_view.pixel_type());
+    let mut dst_view = dst_image.view_mut();
+    let mut resizer = fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3));
+    resizer.resize(&src_view, &mut dst_view)?;
+    Ok(dst_image)
+}
+
+/// How `cover_fit` picks its crop window, bundled into one argument to stay under clippy's
+/// too-many-arguments limit.
+struct CropWindow {
+    gravity: Gravity,
+    smart: bool,
+}
+
+/// Scales (down) to fill `valid_w`x`valid_h` and crops the overhang according to `window.gravity` (or,
+/// with `window.smart` set, the window that maximizes visual detail). Errors if the source is
+/// smaller than the target in either dimension, since there would be nothing to crop.
+fn cover_fit(
+    src_view: fr::DynamicImageView,
+    img_w: u32,
+    img_h: u32,
+    img_ratio: f64,
+    valid_w: NonZeroU32,
+    valid_h: NonZeroU32,
+    window: CropWindow,
+) -> Result<fr::Image> {
+    if img_w < valid_w.get() || img_h < valid_h.get() {
+        return Err(anyhow!(
+            "Image too small, skipping: {}x{} < {}x{}",
+            img_w,
+            img_h,
+            valid_w,
+            valid_h
+        ));
+    }
+
+    let (resize_w, resize_h) = if img_ratio > valid_w.get() as f64 / valid_h.get() as f64 {
+        // If the image is more "landscape" than the target, match its height to the target height
+        (
+            (img_w as f64 * valid_h.get() as f64 / img_h as f64).round() as u32,
+            valid_h.get(),
+        )
+    } else {
+        // If the image is more "portrait" or equal to the target, match its width to the target width
+        (
+            valid_w.get(),
+            (valid_w.get() as f64 * img_h as f64 / img_w as f64).round() as u32,
+        )
+    };
+
+    let resized_image = resize_image(
+        src_view,
+        NonZeroU32::new(resize_w).ok_or_else(|| anyhow!("Invalid resize width"))?,
+        NonZeroU32::new(resize_h).ok_or_else(|| anyhow!("Invalid resize height"))?,
+    )
+    .with_context(|| "Failed to resize image")?;
+
+    let centering = if window.smart {
+        smart_centering(&resized_image, valid_w, valid_h)
+            .with_context(|| "Failed to compute smart crop window")?
+    } else {
+        window.gravity.centering()
+    };
+
+    let cropped_image = crop_image(resized_image.view(), valid_w, valid_h, centering)
+        .with_context(|| "Failed to crop image")?;
+
+    Ok(cropped_image.copy())
+}
+
+/// Scales the source to fit entirely inside `valid_w`x`valid_h`, keeping aspect ratio, then
+/// pastes it centered onto a `pad_color` canvas of the target size. Unlike `cover_fit`, this
+/// never skips small images.
+fn pad_fit(
+    src_view: fr::DynamicImageView,
+    img_w: u32,
+    img_h: u32,
+    valid_w: NonZeroU32,
+    valid_h: NonZeroU32,
+    pad_color: [u8; 3],
+) -> Result<fr::Image> {
+    let scale = (valid_w.get() as f64 / img_w as f64).min(valid_h.get() as f64 / img_h as f64);
+    let resize_w = NonZeroU32::new(((img_w as f64 * scale).round() as u32).max(1))
+        .ok_or_else(|| anyhow!("Invalid resize width"))?;
+    let resize_h = NonZeroU32::new(((img_h as f64 * scale).round() as u32).max(1))
+        .ok_or_else(|| anyhow!("Invalid resize height"))?;
+
+    let resized_image =
+        resize_image(src_view, resize_w, resize_h).with_context(|| "Failed to resize image")?;
+
+    let offset_x = (valid_w.get() - resize_w.get()) / 2;
+    let offset_y = (valid_h.get() - resize_h.get()) / 2;
+
+    paste_on_canvas(&resized_image, valid_w, valid_h, offset_x, offset_y, pad_color)
+}
+
+/// Number of color/alpha channels carried by a `fr::PixelType`.
+fn pixel_type_channels(pixel_type: fr::PixelType) -> Result<usize> {
+    match pixel_type {
+        fr::PixelType::U8x3 | fr::PixelType::U16x3 => Ok(3),
+        fr::PixelType::U8x4 | fr::PixelType::U16x4 => Ok(4),
+        other => Err(anyhow!("Unsupported pixel type: {:?}", other)),
+    }
+}
+
+/// Byte width of a single pixel for a `fr::PixelType`.
+fn bytes_per_pixel(pixel_type: fr::PixelType) -> Result<usize> {
+    let channel_bytes = match pixel_type {
+        fr::PixelType::U8x3 | fr::PixelType::U8x4 => 1,
+        fr::PixelType::U16x3 | fr::PixelType::U16x4 => 2,
+        other => return Err(anyhow!("Unsupported pixel type: {:?}", other)),
+    };
+    Ok(pixel_type_channels(pixel_type)? * channel_bytes)
+}
+
+/// Renders an 8-bit RGB `pad_color` as the raw pixel bytes for `pixel_type`, adding an opaque
+/// alpha channel and widening to 16 bits as needed.
+fn pad_color_bytes(pixel_type: fr::PixelType, color: [u8; 3]) -> Result<Vec<u8>> {
+    let channels = pixel_type_channels(pixel_type)?;
+    match pixel_type {
+        fr::PixelType::U8x3 => Ok(color.to_vec()),
+        fr::PixelType::U8x4 => Ok(vec![color[0], color[1], color[2], u8::MAX]),
+        fr::PixelType::U16x3 | fr::PixelType::U16x4 => {
+            let mut bytes = Vec::with_capacity(channels * 2);
+            for &c in &color {
+                bytes.extend_from_slice(&((c as u16) * 257).to_ne_bytes());
+            }
+            if channels == 4 {
+                bytes.extend_from_slice(&u16::MAX.to_ne_bytes());
+            }
+            Ok(bytes)
+        }
+        other => Err(anyhow!("Unsupported pixel type: {:?}", other)),
+    }
+}
+
+/// Builds a `dst_w`x`dst_h` canvas filled with `pad_color` and copies `src` into it at
+/// `(offset_x, offset_y)`.
+fn paste_on_canvas(
+    src: &fr::Image,
+    dst_w: NonZeroU32,
+    dst_h: NonZeroU32,
+    offset_x: u32,
+    offset_y: u32,
+    pad_color: [u8; 3],
+) -> Result<fr::Image> {
+    let bytes_per_pixel = bytes_per_pixel(src.pixel_type())?;
+    let color_bytes = pad_color_bytes(src.pixel_type(), pad_color)?;
+
+    let mut canvas = fr::Image::new(dst_w, dst_h, src.pixel_type());
+    for chunk in canvas.buffer_mut().chunks_mut(bytes_per_pixel) {
+        chunk.copy_from_slice(&color_bytes);
+    }
+
+    let src_w = src.width().get() as usize;
+    let src_h = src.height().get() as usize;
+    let src_row_stride = src_w * bytes_per_pixel;
+    let dst_row_stride = dst_w.get() as usize * bytes_per_pixel;
+    let src_buffer = src.buffer();
+
+    let canvas_buffer = canvas.buffer_mut();
+    for row in 0..src_h {
+        let dst_start =
+            (offset_y as usize + row) * dst_row_stride + offset_x as usize * bytes_per_pixel;
+        let src_start = row * src_row_stride;
+        canvas_buffer[dst_start..dst_start + src_row_stride]
+            .copy_from_slice(&src_buffer[src_start..src_start + src_row_stride]);
+    }
+
+    Ok(canvas)
+}
+
+/// Converts a native-endian byte buffer back into `u16` samples, as stored by `fr::Image` for
+/// `U16x3`/`U16x4` pixel types.
+fn bytes_to_u16(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+        .collect()
+}
+
+/// Builds a single-channel luminance buffer from an RGB(A)/8- or 16-bit `fr::Image`, for use by
+/// `smart_centering`'s energy scan.
+fn luma_image(image: &fr::Image) -> Result<fr::Image> {
+    let bytes_per_pixel = bytes_per_pixel(image.pixel_type())?;
+    let is_u16 = matches!(image.pixel_type(), fr::PixelType::U16x3 | fr::PixelType::U16x4);
+
+    let sample = |pixel: &[u8], channel: usize| -> f64 {
+        if is_u16 {
+            u16::from_ne_bytes([pixel[channel * 2], pixel[channel * 2 + 1]]) as f64 / 257.0
+        } else {
+            pixel[channel] as f64
+        }
+    };
+
+    let luma: Vec<u8> = image
+        .buffer()
+        .chunks_exact(bytes_per_pixel)
+        .map(|pixel| {
+            let (r, g, b) = (sample(pixel, 0), sample(pixel, 1), sample(pixel, 2));
+            (0.299 * r + 0.587 * g + 0.114 * b).round().clamp(0.0, 255.0) as u8
+        })
+        .collect();
+
+    fr::Image::from_vec_u8(image.width(), image.height(), luma, fr::PixelType::U8)
+        .context("Failed to build luminance buffer")
+}
+
+/// Sum of Sobel gradient magnitudes over the `w`x`h` window at `(x0, y0)` in a `width`x`height`
+/// grayscale buffer, used as a cheap proxy for "visual content" when picking a smart crop window.
+fn sobel_energy(luma: &[u8], width: usize, height: usize, x0: usize, y0: usize, w: usize, h: usize) -> f64 {
+    let at = |x: usize, y: usize| luma[y * width + x] as f64;
+
+    let x_start = x0.max(1);
+    let y_start = y0.max(1);
+    let x_end = (x0 + w).min(width.saturating_sub(1));
+    let y_end = (y0 + h).min(height.saturating_sub(1));
+
+    let mut energy = 0.0;
+    for y in y_start..y_end {
+        for x in x_start..x_end {
+            let gx = -at(x - 1, y - 1) - 2.0 * at(x - 1, y) - at(x - 1, y + 1)
+                + at(x + 1, y - 1)
+                + 2.0 * at(x + 1, y)
+                + at(x + 1, y + 1);
+            let gy = -at(x - 1, y - 1) - 2.0 * at(x, y - 1) - at(x + 1, y - 1)
+                + at(x - 1, y + 1)
+                + 2.0 * at(x, y + 1)
+                + at(x + 1, y + 1);
+            energy += gx.abs() + gy.abs();
+        }
+    }
+    energy
+}
+
+/// Slides the `crop_w`x`crop_h` window along whichever axis of `resized` is over-sized, scoring
+/// each candidate position by Sobel energy on a downscaled grayscale copy, and returns the
+/// centering fraction of the highest-scoring position (ties break toward the center).
+fn smart_centering(resized: &fr::Image, crop_w: NonZeroU32, crop_h: NonZeroU32) -> Result<(f64, f64)> {
+    const MAX_ENERGY_DIM: u32 = 256;
+    const STEPS: u32 = 24;
+
+    let img_w = resized.width().get();
+    let img_h = resized.height().get();
+    let slack_w = img_w.saturating_sub(crop_w.get());
+    let slack_h = img_h.saturating_sub(crop_h.get());
+
+    if slack_w == 0 && slack_h == 0 {
+        return Ok((0.5, 0.5));
+    }
+
+    let luma = luma_image(resized)?;
+    let scale = (MAX_ENERGY_DIM as f64 / img_w.max(img_h) as f64).min(1.0);
+    let small_w = NonZeroU32::new(((img_w as f64 * scale).round() as u32).max(1))
+        .ok_or_else(|| anyhow!("Invalid downscale width"))?;
+    let small_h = NonZeroU32::new(((img_h as f64 * scale).round() as u32).max(1))
+        .ok_or_else(|| anyhow!("Invalid downscale height"))?;
+    let small_luma = resize_image(luma.view(), small_w, small_h)?;
+
+    let small_w = small_w.get() as usize;
+    let small_h = small_h.get() as usize;
+    let small_crop_w = ((crop_w.get() as f64 * scale).round() as usize).clamp(1, small_w);
+    let small_crop_h = ((crop_h.get() as f64 * scale).round() as usize).clamp(1, small_h);
+    let small_slack_w = small_w.saturating_sub(small_crop_w);
+    let small_slack_h = small_h.saturating_sub(small_crop_h);
+    let buffer = small_luma.buffer();
+
+    let mut best_fraction = 0.5;
+    let mut best_score = f64::MIN;
+
+    let slide_horizontally = slack_w >= slack_h;
+    for step in 0..=STEPS {
+        let fraction = step as f64 / STEPS as f64;
+        let score = if slide_horizontally {
+            let x0 = (fraction * small_slack_w as f64).round() as usize;
+            sobel_energy(buffer, small_w, small_h, x0, 0, small_crop_w, small_h)
+        } else {
+            let y0 = (fraction * small_slack_h as f64).round() as usize;
+            sobel_energy(buffer, small_w, small_h, 0, y0, small_w, small_crop_h)
+        };
+
+        let closer_to_center = (fraction - 0.5).abs() < (best_fraction - 0.5).abs();
+        if score > best_score || (score == best_score && closer_to_center) {
+            best_score = score;
+            best_fraction = fraction;
+        }
+    }
+
+    Ok(if slide_horizontally {
+        (best_fraction, 0.5)
+    } else {
+        (0.5, best_fraction)
+    })
+}
+
+/// Runs the mode dispatch and returns the resized/cropped image along with the target
+/// resolution that was picked, so callers (e.g. the CLI's `--manifest`) can report the decision
+/// without re-deriving it.
+fn resize_and_crop(
+    src_view: fr::DynamicImageView,
+    res: &[(u32, u32)],
+    mode: FitMode,
+    pad_color: [u8; 3],
+    gravity: Gravity,
+    smart: bool,
+) -> Result<(fr::Image, (u32, u32))> {
+    // Calculate aspect ratio of the image
+    let img_w = src_view.width().get();
+    let img_h = src_view.height().get();
+    let img_ratio = img_w as f64 / img_h as f64;
+
+    // Find the resolution with the closest aspect ratio
+    let &(valid_w, valid_h) = res
+        .iter()
+        .min_by(|&&(w1, h1), &&(w2, h2)| {
+            let ratio1 = (img_ratio - w1 as f64 / h1 as f64).abs();
+            let ratio2 = (img_ratio - w2 as f64 / h2 as f64).abs();
+            ratio1.partial_cmp(&ratio2).unwrap_or(Ordering::Equal)
+        })
+        .ok_or_else(|| anyhow!("Could not find a valid resolution target"))?;
+
+    let valid_w = NonZeroU32::new(valid_w).ok_or_else(|| anyhow!("Invalid target width"))?;
+    let valid_h = NonZeroU32::new(valid_h).ok_or_else(|| anyhow!("Invalid target height"))?;
+
+    let image = match mode {
+        FitMode::Cover => cover_fit(
+            src_view,
+            img_w,
+            img_h,
+            img_ratio,
+            valid_w,
+            valid_h,
+            CropWindow { gravity, smart },
+        ),
+        FitMode::Pad => pad_fit(src_view, img_w, img_h, valid_w, valid_h, pad_color),
+        FitMode::Stretch => {
+            resize_image(src_view, valid_w, valid_h).with_context(|| "Failed to resize image")
+        }
+    }?;
+
+    Ok((image, (valid_w.get(), valid_h.get())))
+}
+
+/// Reassembles the pixel buffer produced by the resize/crop pipeline into a `DynamicImage`,
+/// picking the variant that matches the pixel type carried through from decoding.
+fn reconstruct_dynamic_image(image: &fr::Image) -> Result<image::DynamicImage> {
+    let width = image.width().get() as u32;
+    let height = image.height().get() as u32;
+    let buffer = image.buffer().to_vec();
+
+    match image.pixel_type() {
+        fr::PixelType::U8x3 => RgbImage::from_raw(width, height, buffer)
+            .map(image::DynamicImage::ImageRgb8)
+            .with_context(|| "Failed to convert to RgbImage"),
+        fr::PixelType::U8x4 => RgbaImage::from_raw(width, height, buffer)
+            .map(image::DynamicImage::ImageRgba8)
+            .with_context(|| "Failed to convert to RgbaImage"),
+        fr::PixelType::U16x3 => {
+            ImageBuffer::<Rgb<u16>, Vec<u16>>::from_raw(width, height, bytes_to_u16(&buffer))
+                .map(image::DynamicImage::ImageRgb16)
+                .with_context(|| "Failed to convert to 16-bit RGB image")
+        }
+        fr::PixelType::U16x4 => {
+            ImageBuffer::<Rgba<u16>, Vec<u16>>::from_raw(width, height, bytes_to_u16(&buffer))
+                .map(image::DynamicImage::ImageRgba16)
+                .with_context(|| "Failed to convert to 16-bit RGBA image")
+        }
+        other => Err(anyhow!("Unsupported pixel type for saving: {:?}", other)),
+    }
+}
+
+/// Flattens `img` down to a pixel type `format`'s encoder can actually write, so a carried-through
+/// alpha channel or 16-bit depth (see [`decode_to_pixel_buffer`]) doesn't turn into an `Unsupported`
+/// encode error on formats that can't represent it: JPEG has no alpha or 16-bit support, and the
+/// `image` crate's WebP encoder is 8-bit only. PNG supports everything we carry, so it passes through.
+fn prepare_for_encoder(img: image::DynamicImage, format: OutputFormat) -> image::DynamicImage {
+    use image::DynamicImage::{ImageRgb8, ImageRgba8};
+
+    match format {
+        OutputFormat::Png => img,
+        OutputFormat::Jpeg => match img {
+            ImageRgb8(_) => img,
+            _ => {
+                debug!(
+                    "Flattening to 8-bit RGB for JPEG output (JPEG doesn't support alpha or 16-bit channels)"
+                );
+                ImageRgb8(img.to_rgb8())
+            }
+        },
+        OutputFormat::Webp => match img {
+            ImageRgb8(_) | ImageRgba8(_) => img,
+            _ => {
+                let has_alpha = img.color().has_alpha();
+                debug!(
+                    "Flattening to 8-bit {} for WebP output (the WebP encoder is 8-bit only)",
+                    if has_alpha { "RGBA" } else { "RGB" }
+                );
+                if has_alpha {
+                    ImageRgba8(img.to_rgba8())
+                } else {
+                    ImageRgb8(img.to_rgb8())
+                }
+            }
+        },
+    }
+}
+
+fn save_image(image: &fr::Image, path: &Path, format: OutputFormat, quality: u8) -> Result<()> {
+    let img = prepare_for_encoder(reconstruct_dynamic_image(image)?, format);
+    let mut file = fs::File::create(path).with_context(|| "Failed to create output file")?;
+
+    match format {
+        OutputFormat::Png => img
+            .write_with_encoder(image::codecs::png::PngEncoder::new(&mut file))
+            .with_context(|| "Failed to save the image"),
+        OutputFormat::Jpeg => img
+            .write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+                &mut file, quality,
+            ))
+            .with_context(|| "Failed to save the image"),
+        OutputFormat::Webp => img
+            .write_with_encoder(image::codecs::webp::WebPEncoder::new_lossless(&mut file))
+            .with_context(|| "Failed to save the image"),
+    }
+}
+
+/// Strips solid-color margins from `img` before the resize/crop step, so `img_ratio` reflects
+/// actual content rather than padding. The background color defaults to the top-left corner
+/// pixel, or `trim_color` when given; a row/column counts as margin when every pixel is within
+/// `tolerance` of it. Always keeps at least a 1x1 region.
+fn trim_to_content(
+    img: &image::DynamicImage,
+    tolerance: u8,
+    trim_color: Option<[u8; 3]>,
+) -> image::DynamicImage {
+    let rgb = img.to_rgb8();
+    let width = rgb.width();
+    let height = rgb.height();
+
+    let within_tolerance = |a: [u8; 3], b: [u8; 3]| {
+        a.iter()
+            .zip(b.iter())
+            .all(|(x, y)| (*x as i32 - *y as i32).unsigned_abs() <= tolerance as u32)
+    };
+
+    let background = trim_color.unwrap_or_else(|| rgb.get_pixel(0, 0).0);
+
+    let row_uniform =
+        |y: u32| (0..width).all(|x| within_tolerance(rgb.get_pixel(x, y).0, background));
+    let col_uniform =
+        |x: u32| (0..height).all(|y| within_tolerance(rgb.get_pixel(x, y).0, background));
+
+    let mut top = 0;
+    while top < height - 1 && row_uniform(top) {
+        top += 1;
+    }
+    let mut bottom = height - 1;
+    while bottom > top && row_uniform(bottom) {
+        bottom -= 1;
+    }
+    let mut left = 0;
+    while left < width - 1 && col_uniform(left) {
+        left += 1;
+    }
+    let mut right = width - 1;
+    while right > left && col_uniform(right) {
+        right -= 1;
+    }
+
+    img.crop_imm(left, top, right - left + 1, bottom - top + 1)
+}
+
+/// Decodes `img` into the raw pixel buffer and `fr::PixelType` that best preserves its color
+/// type: 8-bit sources with alpha (RGBA8, LA8) become 8-bit RGBA, 16-bit sources keep their bit
+/// depth (and alpha, if any), everything else flattens to 8-bit RGB. `force_rgb` always flattens to 8-bit RGB, discarding alpha and extra bit depth.
+fn decode_to_pixel_buffer(img: &image::DynamicImage, force_rgb: bool) -> (fr::PixelType, Vec<u8>) {
+    if force_rgb {
+        return (fr::PixelType::U8x3, img.to_rgb8().into_raw());
+    }
+
+    match img.color() {
+        image::ColorType::Rgba8 | image::ColorType::La8 => {
+            (fr::PixelType::U8x4, img.to_rgba8().into_raw())
+        }
+        image::ColorType::Rgb16 | image::ColorType::Luma16 => {
+            let mut bytes = Vec::new();
+            for sample in img.to_rgb16().into_raw() {
+                bytes.extend_from_slice(&sample.to_ne_bytes());
+            }
+            (fr::PixelType::U16x3, bytes)
+        }
+        image::ColorType::Rgba16 | image::ColorType::La16 => {
+            let mut bytes = Vec::new();
+            for sample in img.to_rgba16().into_raw() {
+                bytes.extend_from_slice(&sample.to_ne_bytes());
+            }
+            (fr::PixelType::U16x4, bytes)
+        }
+        _ => (fr::PixelType::U8x3, img.to_rgb8().into_raw()),
+    }
+}
+
+/// Where one source image ended up and what decisions were made getting it there, for callers
+/// that want to audit a batch run (e.g. the CLI's `--manifest`).
+#[derive(Clone, Debug)]
+pub struct ProcessedImage {
+    pub output_path: PathBuf,
+    pub original_dimensions: (u32, u32),
+    pub target_resolution: (u32, u32),
+}
+
+/// Runs one source image through the full pipeline: decode, optional trim, fit/crop to the
+/// resolution in `config.resolutions` closest to the source aspect ratio, and encode into
+/// `output_path` under a blake3 hash of the source bytes. Returns the output path and the
+/// decisions made along the way, or an error (e.g. "already exists", "image too small") that
+/// callers typically just log and skip.
+pub fn crop_image_to_resolutions(
+    path: &Path,
+    output_path: &Path,
+    config: &SnapcropConfig,
+) -> Result<ProcessedImage> {
+    let data = fs::read(path).context("Failed to read image file")?;
+    let image_name = format!(
+        "{}.{}",
+        hash(&data).to_hex(),
+        config.image_format.extension()
+    );
+    let output_image_path = output_path.join(&image_name);
+
+    if output_image_path.exists() {
+        return Err(anyhow!(
+            "Image already exists in output dir, skipping: {}",
+            image_name
+        ));
+    }
+
+    let img = ImageReader::open(path)
+        .with_context(|| format!("Failed to open image from path: {}", path.display()))?
+        .with_guessed_format()?
+        .decode()
+        .context("Failed to decode image")?;
+
+    let original_dimensions = (img.width(), img.height());
+
+    let img = if config.trim {
+        trim_to_content(&img, config.trim_tolerance, config.trim_color)
+    } else {
+        img
+    };
+
+    let width = NonZeroU32::new(img.width()).ok_or_else(|| anyhow!("Invalid image width"))?;
+    let height = NonZeroU32::new(img.height()).ok_or_else(|| anyhow!("Invalid image height"))?;
+
+    let (pixel_type, raw) = decode_to_pixel_buffer(&img, config.force_rgb);
+    let src_image = fr::Image::from_vec_u8(width, height, raw, pixel_type)
+        .context("Failed to create image from vector")?;
+
+    let (resized_cropped_image, target_resolution) = resize_and_crop(
+        src_image.view(),
+        &config.resolutions,
+        config.mode,
+        config.pad_color,
+        config.gravity,
+        config.smart,
+    )?;
+
+    save_image(
+        &resized_cropped_image,
+        &output_image_path,
+        config.image_format,
+        config.quality,
+    )?;
+
+    Ok(ProcessedImage {
+        output_path: output_image_path,
+        original_dimensions,
+        target_resolution,
+    })
+}
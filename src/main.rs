@@ -1,84 +1,19 @@
 use anyhow::{anyhow, Context, Result};
-use blake3::hash;
 use clap::Parser;
-use fast_image_resize as fr;
-use image::io::Reader as ImageReader;
-use image::RgbImage;
 use indicatif::ParallelProgressIterator;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::debug;
-use nom::branch::alt;
-use nom::character::complete::{char, digit1, multispace0};
-use nom::combinator::{map, map_res, opt};
-use nom::multi::separated_list0;
-use nom::sequence::{preceded, separated_pair, terminated, tuple};
-use nom::IResult;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use simplelog::SharedLogger;
-use std::cmp::Ordering;
-use std::io::BufRead;
-use std::num::NonZeroU32;
+use snapcrop::{
+    crop_image_to_resolutions, parse_color, parse_resolutions, FitMode, Gravity, OutputFormat,
+    ProcessedImage, SnapcropConfig,
+};
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::{fs, io};
 
-fn parse_u32(input: &str) -> IResult<&str, u32> {
-    map_res(digit1, |digit_str: &str| digit_str.parse::<u32>())(input)
-}
-
-fn parse_bidirectional_resolution(input: &str) -> IResult<&str, Vec<(u32, u32)>> {
-    map(
-        preceded(char('['), terminated(parse_sizes, char(']'))),
-        |res| {
-            res.iter()
-                .flat_map(|&(width, height)| vec![(width, height), (height, width)])
-                .collect()
-        },
-    )(input)
-}
-
-fn parse_resolution(input: &str) -> IResult<&str, Vec<(u32, u32)>> {
-    alt((parse_sizes, parse_bidirectional_resolution))(input)
-}
-
-fn parse_range(input: &str) -> IResult<&str, Vec<u32>> {
-    let (input, (start, _, end, step)) = tuple((
-        parse_u32,
-        char(':'),
-        parse_u32,
-        opt(preceded(char(':'), parse_u32)),
-    ))(input)?;
-    Ok((input, generate_values((start, end, step.unwrap_or(1)))))
-}
-
-fn generate_values((start, end, step): (u32, u32, u32)) -> Vec<u32> {
-    (start..=end).step_by(step as usize).collect()
-}
-
-fn parse_size(input: &str) -> IResult<&str, Vec<u32>> {
-    alt((parse_range, map(parse_u32, |size| vec![size])))(input)
-}
-
-fn parse_sizes(input: &str) -> IResult<&str, Vec<(u32, u32)>> {
-    let (input, (widths, heights)) = alt((
-        separated_pair(parse_size, char('x'), parse_size),
-        map(parse_size, |sizes| (sizes.clone(), sizes.clone())),
-    ))(input)?;
-
-    Ok((
-        input,
-        widths
-            .iter()
-            .flat_map(|w| heights.iter().map(|h| (*w, *h)))
-            .collect(),
-    ))
-}
-
-fn parse_resolutions(input: &str) -> IResult<&str, Vec<(u32, u32)>> {
-    let (input, res_list) =
-        separated_list0(terminated(char(','), multispace0), parse_resolution)(input)?;
-    Ok((input, res_list.into_iter().flatten().collect()))
-}
-
 #[derive(Parser, Debug)]
 #[command(long_about = "
 Crop all your images with snapping
@@ -123,137 +58,104 @@ struct Args {
     #[arg(short = 'v', long = "verbose")]
     verbose: bool,
 
-    // Image format to save files with
-    #[arg(short = 'f', long = "format", default_value = "png")]
-    image_format: String,
-}
+    /// Image format to save files with, selects the encoder directly
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = OutputFormat::Png)]
+    image_format: OutputFormat,
 
-fn resize_image(
-    src_view: fr::DynamicImageView,
-    resize_w: NonZeroU32,
-    resize_h: NonZeroU32,
-) -> Result<fr::Image> {
-    let mut dst_image = fr::Image::new(resize_w, resize_h, src_view.pixel_type());
-    let mut dst_view = dst_image.view_mut();
-    let mut resizer = fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3));
-    resizer.resize(&src_view, &mut dst_view)?;
-    Ok(dst_image)
-}
+    /// JPEG encoding quality, 1-100. Has no effect on PNG or WebP output, which the `image` crate
+    /// always writes lossless.
+    #[arg(long = "quality", default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: u8,
 
-fn crop_image(
-    mut src_view: fr::DynamicImageView,
-    crop_w: NonZeroU32,
-    crop_h: NonZeroU32,
-) -> Result<fr::Image> {
-    src_view.set_crop_box_to_fit_dst_size(crop_w, crop_h, None);
-    let mut dst_image = fr::Image::new(crop_w, crop_h, src_view.pixel_type());
-    let mut dst_view = dst_image.view_mut();
-    let mut resizer = fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3));
-    resizer.resize(&src_view, &mut dst_view)?;
-    Ok(dst_image)
-}
+    /// How to fit the source image into the target resolution
+    #[arg(short = 'm', long = "mode", value_enum, default_value_t = FitMode::Cover)]
+    mode: FitMode,
 
-fn resize_and_crop(src_view: fr::DynamicImageView, res: Vec<(u32, u32)>) -> Result<fr::Image> {
-    // Calculate aspect ratio of the image
-    let img_w = src_view.width().get();
-    let img_h = src_view.height().get();
-    let img_ratio = img_w as f64 / img_h as f64;
+    /// Background color used to fill the padding in `pad` mode, as "R,G,B"
+    #[arg(long = "pad-color", default_value = "0,0,0")]
+    pad_color: String,
 
-    // Find the resolution with the closest aspect ratio
-    let &(valid_w, valid_h) = res
-        .iter()
-        .min_by(|&&(w1, h1), &&(w2, h2)| {
-            let ratio1 = (img_ratio - w1 as f64 / h1 as f64).abs();
-            let ratio2 = (img_ratio - w2 as f64 / h2 as f64).abs();
-            ratio1.partial_cmp(&ratio2).unwrap_or(Ordering::Equal)
-        })
-        .ok_or_else(|| anyhow!("Could not find a valid resolution target"))?;
+    /// Flatten to 8-bit RGB, discarding alpha and extra bit depth
+    #[arg(long = "force-rgb")]
+    force_rgb: bool,
 
-    println!("{}x{} -> {}x{}", img_w, img_h, valid_w, valid_h);
+    /// Crop gravity for `cover` mode, ignored when `--smart` is set
+    #[arg(long = "gravity", value_enum, default_value_t = Gravity::Center)]
+    gravity: Gravity,
 
-    if img_w < valid_w || img_h < valid_h {
-        return Err(anyhow!(
-            "Image too small, skipping: {}x{} < {}x{}",
-            img_w,
-            img_h,
-            valid_w,
-            valid_h
-        ));
-    }
-
-    let (resize_w, resize_h) = if img_ratio > valid_w as f64 / valid_h as f64 {
-        // If the image is more "landscape" than the target, match its height to the target height
-        ((img_w as f64 * valid_h as f64 / img_h as f64).round() as u32, valid_h)
-    } else {
-        // If the image is more "portrait" or equal to the target, match its width to the target width
-        (valid_w, (valid_w as f64 * img_h as f64 / img_w as f64).round() as u32)
-    };
+    /// Pick the `cover` crop window that maximizes visual detail instead of a fixed gravity
+    #[arg(long = "smart")]
+    smart: bool,
 
-    // Resize the image while maintaining its original aspect ratio
-    let resized_image = resize_image(
-        src_view,
-        NonZeroU32::new(resize_w).ok_or_else(|| anyhow!("Invalid resize width"))?,
-        NonZeroU32::new(resize_h).ok_or_else(|| anyhow!("Invalid resize height"))?,
-    )
-    .with_context(|| "Failed to resize image")?;
-
-    // Crop the resized image to the exact dimensions of the chosen valid resolution
-    let cropped_image = crop_image(
-        resized_image.view(),
-        NonZeroU32::new(valid_w).ok_or_else(|| anyhow!("Invalid target width"))?,
-        NonZeroU32::new(valid_h).ok_or_else(|| anyhow!("Invalid target height"))?,
-    )
-    .with_context(|| "Failed to crop image")?;
-
-    Ok(cropped_image.copy())
-}
+    /// Strip uniform-color borders (scans, letterboxing, sprite padding) before resizing
+    #[arg(long = "trim")]
+    trim: bool,
 
-fn save_image(image: &fr::Image, path: &Path) -> Result<()> {
-    let width = image.width().get() as u32;
-    let height = image.height().get() as u32;
-    let buffer = image.buffer().to_vec();
+    /// Maximum per-channel color distance from the background still considered uniform, for `--trim`
+    #[arg(long = "trim-tolerance", default_value_t = 10)]
+    trim_tolerance: u8,
 
-    let img = RgbImage::from_raw(width, height, buffer)
-        .with_context(|| "Failed to convert to RgbImage")?;
+    /// Override the auto-detected background color for `--trim`, as "R,G,B"
+    #[arg(long = "trim-color")]
+    trim_color: Option<String>,
 
-    img.save(path).with_context(|| "Failed to save the image")
+    /// Write a CSV manifest (source path, output filename, original/target dimensions, fit mode,
+    /// skip reason) mapping hashed outputs back to where they came from
+    #[arg(long = "manifest")]
+    manifest: Option<PathBuf>,
 }
 
-fn process_image(
-    path: &Path,
-    output_path: &Path,
-    image_format: &str,
-    res: Vec<(u32, u32)>,
-) -> Result<()> {
-    let data = fs::read(path).context("Failed to read image file")?;
-    let image_name = format!("{}.{}", hash(&data).to_hex(), image_format);
-    let output_image_path = output_path.join(&image_name);
-
-    if output_image_path.exists() {
-        return Err(anyhow!(
-            "Image already exists in output dir, skipping: {}",
-            image_name
-        ));
+/// Escapes a CSV field, quoting it if it contains a comma, quote, or newline.
+fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
+}
 
-    let img = ImageReader::open(path)
-        .with_context(|| format!("Failed to open image from path: {}", path.display()))?
-        .with_guessed_format()?
-        .decode()
-        .context("Failed to decode image")?;
-
-    let width = NonZeroU32::new(img.width()).ok_or_else(|| anyhow!("Invalid image width"))?;
-    let height = NonZeroU32::new(img.height()).ok_or_else(|| anyhow!("Invalid image height"))?;
-
-    let src_image =
-        fr::Image::from_vec_u8(width, height, img.to_rgb8().into_raw(), fr::PixelType::U8x3)
-            .context("Failed to create image from vector")?;
-
-    let resized_cropped_image = resize_and_crop(src_image.view(), res);
+/// Renders one manifest row for `source`, either the dimensions/target resolution of a
+/// successfully processed image, or the error that caused it to be skipped.
+fn manifest_row(source: &str, mode: FitMode, outcome: &Result<ProcessedImage>) -> String {
+    let fit_mode = match mode {
+        FitMode::Cover => "cover",
+        FitMode::Pad => "pad",
+        FitMode::Stretch => "stretch",
+    };
 
-    save_image(&resized_cropped_image?, &output_image_path)?;
+    let fields = match outcome {
+        Ok(processed) => [
+            source.to_string(),
+            processed
+                .output_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            processed.original_dimensions.0.to_string(),
+            processed.original_dimensions.1.to_string(),
+            processed.target_resolution.0.to_string(),
+            processed.target_resolution.1.to_string(),
+            fit_mode.to_string(),
+            String::new(),
+        ],
+        Err(e) => [
+            source.to_string(),
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+            fit_mode.to_string(),
+            e.to_string(),
+        ],
+    };
 
-    Ok(())
+    fields
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+        + "\n"
 }
 
 fn main() -> Result<()> {
@@ -280,9 +182,7 @@ fn main() -> Result<()> {
 
     simplelog::CombinedLogger::init(loggers).context("Failed to initialize logger")?;
 
-    let mut res: Vec<(u32, u32)> = parse_resolutions(&args.resolutions)
-        .map_err(|e| anyhow!("Failed to parse resolutions: {}", e))
-        .and_then(|(_, res)| Ok(res))?;
+    let mut res = parse_resolutions(&args.resolutions)?;
 
     res.sort();
     res.dedup();
@@ -295,6 +195,20 @@ fn main() -> Result<()> {
 
     debug!("Resolutions: {:?}", res);
 
+    let config = SnapcropConfig {
+        resolutions: res,
+        mode: args.mode,
+        pad_color: parse_color(&args.pad_color)?,
+        gravity: args.gravity,
+        smart: args.smart,
+        force_rgb: args.force_rgb,
+        trim: args.trim,
+        trim_tolerance: args.trim_tolerance,
+        trim_color: args.trim_color.as_deref().map(parse_color).transpose()?,
+        image_format: args.image_format,
+        quality: args.quality,
+    };
+
     let image_paths: Vec<String> = match args.image_list_path {
         Some(image_list_path) => fs::read_to_string(image_list_path)
             .with_context(|| "Failed to read image list file")?
@@ -304,6 +218,19 @@ fn main() -> Result<()> {
         None => io::stdin().lock().lines().filter_map(Result::ok).collect(),
     };
 
+    let manifest = args
+        .manifest
+        .map(|manifest_path| -> Result<Mutex<fs::File>> {
+            let mut file = fs::File::create(&manifest_path)
+                .with_context(|| "Failed to create manifest file")?;
+            file.write_all(
+                "source_path,output_filename,original_width,original_height,target_width,target_height,fit_mode,skip_reason\n"
+                    .as_bytes(),
+            )?;
+            Ok(Mutex::new(file))
+        })
+        .transpose()?;
+
     let pb = ProgressBar::new(image_paths.len() as u64);
 
     pb.set_style(
@@ -315,14 +242,20 @@ fn main() -> Result<()> {
     );
 
     image_paths.par_iter().progress_with(pb).for_each(|path| {
-        if let Err(e) = process_image(
-            Path::new(&path),
-            &args.output_path,
-            &args.image_format,
-            res.to_owned(),
-        ) {
+        let outcome = crop_image_to_resolutions(Path::new(&path), &args.output_path, &config);
+
+        if let Err(e) = &outcome {
             debug!("{}: {}", path, e);
         }
+
+        if let Some(manifest) = &manifest {
+            let row = manifest_row(path, config.mode, &outcome);
+            if let Ok(mut file) = manifest.lock() {
+                if let Err(e) = file.write_all(row.as_bytes()) {
+                    debug!("Failed to write manifest row for {}: {}", path, e);
+                }
+            }
+        }
     });
 
     Ok(())